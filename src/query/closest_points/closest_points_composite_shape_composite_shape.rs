@@ -0,0 +1,248 @@
+use crate::bounding_volume::Aabb;
+use crate::math::{Isometry, Real, Vector};
+use crate::query::{ClosestPoints, QueryDispatcher};
+use crate::shape::TypedSimdCompositeShape;
+use na;
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+
+/// Closest points between two composite shapes.
+pub fn closest_points_composite_shape_composite_shape<D, G1, G2>(
+    dispatcher: &D,
+    pos12: &Isometry<Real>,
+    g1: &G1,
+    g2: &G2,
+    margin: Real,
+) -> ClosestPoints
+where
+    D: ?Sized + QueryDispatcher,
+    G1: ?Sized + TypedSimdCompositeShape,
+    G2: ?Sized + TypedSimdCompositeShape,
+{
+    let qbvh1 = g1.typed_qbvh();
+    let qbvh2 = g2.typed_qbvh();
+    let nodes1 = qbvh1.raw_nodes();
+    let nodes2 = qbvh2.raw_nodes();
+    let proxies1 = qbvh1.raw_proxies();
+    let proxies2 = qbvh2.raw_proxies();
+
+    if nodes1.is_empty() || nodes2.is_empty() {
+        return ClosestPoints::Disjoint;
+    }
+
+    // A single priority queue of node-pair candidates, one member from each tree, ordered
+    // by the lower bound on the distance between their Aabbs (in g1's local frame). We seed
+    // it with every pair of g1's and g2's top-level children, then keep popping the
+    // smallest-bound pair and descending the larger-volume side until a leaf/leaf pair is
+    // reached or the bound exceeds the best exact distance found so far.
+    let mut queue: BinaryHeap<Reverse<Candidate>> = BinaryHeap::new();
+    let mut best = ClosestPoints::Disjoint;
+    let mut best_dist = Real::MAX;
+
+    for lane1 in 0..4 {
+        if nodes1[0].children[lane1] == u32::MAX {
+            continue;
+        }
+        let aabb1 = nodes1[0].simd_aabb.extract(lane1);
+
+        for lane2 in 0..4 {
+            if nodes2[0].children[lane2] == u32::MAX {
+                continue;
+            }
+            let aabb2 = nodes2[0].simd_aabb.extract(lane2).transform_by(pos12);
+
+            queue.push(Reverse(Candidate {
+                lower_bound: aabb_lower_bound_distance(&aabb1, &aabb2),
+                child1: ChildRef { node: 0, lane: lane1 as u8 },
+                child2: ChildRef { node: 0, lane: lane2 as u8 },
+            }));
+        }
+    }
+
+    while let Some(Reverse(candidate)) = queue.pop() {
+        if candidate.lower_bound >= best_dist {
+            break;
+        }
+
+        let leaf1 = nodes1[candidate.child1.node as usize].leaf;
+        let leaf2 = nodes2[candidate.child2.node as usize].leaf;
+        let child1 = nodes1[candidate.child1.node as usize].children[candidate.child1.lane as usize];
+        let child2 = nodes2[candidate.child2.node as usize].children[candidate.child2.lane as usize];
+
+        if leaf1 && leaf2 {
+            let part_id1 = proxies1[child1 as usize].data;
+            let part_id2 = proxies2[child2 as usize].data;
+            let mut found_intersection = false;
+
+            g1.map_untyped_part_at(part_id1, |part_pos1, part1, _| {
+                g2.map_untyped_part_at(part_id2, |part_pos2, part2, _| {
+                    let pos_part1_part2 = part_pos1.inv_mul(pos12) * part_pos2;
+                    let pts = dispatcher.closest_points(&pos_part1_part2, part1, part2, margin);
+
+                    if let Ok(ClosestPoints::WithinMargin(p1, p2)) = pts {
+                        // `p2` must be returned in g2's own local frame (see the sibling
+                        // single-composite visitor), so only transform it as far as
+                        // part2-local -> g2-local. The `p2_1` temp, in g1's frame, is used
+                        // solely for the distance comparison below.
+                        let p1 = part_pos1.transform_point(&p1);
+                        let p2 = part_pos2 * p2;
+                        let p2_1 = pos12 * p2;
+                        let dist = na::distance(&p1, &p2_1);
+
+                        if dist < best_dist {
+                            best_dist = dist;
+                            best = ClosestPoints::WithinMargin(p1, p2);
+                        }
+                    } else if let Ok(ClosestPoints::Intersecting) = pts {
+                        found_intersection = true;
+                    }
+                });
+            });
+
+            if found_intersection {
+                return ClosestPoints::Intersecting;
+            }
+        } else {
+            // Descend whichever side currently has the larger-volume Aabb, pairing each of
+            // its four children against the other (un-descended) side.
+            let aabb1 = nodes1[candidate.child1.node as usize]
+                .simd_aabb
+                .extract(candidate.child1.lane as usize);
+            let aabb2 = nodes2[candidate.child2.node as usize]
+                .simd_aabb
+                .extract(candidate.child2.lane as usize)
+                .transform_by(pos12);
+
+            let descend_first = !leaf1 && (leaf2 || aabb1.volume() >= aabb2.volume());
+
+            if descend_first {
+                let group = &nodes1[child1 as usize];
+                for lane in 0..4 {
+                    if group.children[lane] == u32::MAX {
+                        continue;
+                    }
+                    let aabb = group.simd_aabb.extract(lane);
+                    queue.push(Reverse(Candidate {
+                        lower_bound: aabb_lower_bound_distance(&aabb, &aabb2),
+                        child1: ChildRef { node: child1, lane: lane as u8 },
+                        child2: candidate.child2,
+                    }));
+                }
+            } else {
+                let group = &nodes2[child2 as usize];
+                for lane in 0..4 {
+                    if group.children[lane] == u32::MAX {
+                        continue;
+                    }
+                    let aabb = group.simd_aabb.extract(lane).transform_by(pos12);
+                    queue.push(Reverse(Candidate {
+                        lower_bound: aabb_lower_bound_distance(&aabb1, &aabb),
+                        child1: candidate.child1,
+                        child2: ChildRef { node: child2, lane: lane as u8 },
+                    }));
+                }
+            }
+        }
+    }
+
+    best
+}
+
+/// A reference to one of the (up to) four children held by a Qbvh node.
+#[derive(Copy, Clone)]
+struct ChildRef {
+    node: u32,
+    lane: u8,
+}
+
+/// A node-pair candidate in the dual-tree priority queue, ordered by its distance lower bound.
+#[derive(Copy, Clone)]
+struct Candidate {
+    lower_bound: Real,
+    child1: ChildRef,
+    child2: ChildRef,
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.lower_bound == other.lower_bound
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.lower_bound.partial_cmp(&other.lower_bound)
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.partial_cmp(other).unwrap_or(Ordering::Equal)
+    }
+}
+
+/// A lower bound on the distance between two (already co-located) Aabbs.
+fn aabb_lower_bound_distance(a: &Aabb, b: &Aabb) -> Real {
+    let gap = (a.mins - b.maxs).sup(&(b.mins - a.maxs)).sup(&Vector::zeros());
+    gap.norm()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::query::DefaultQueryDispatcher;
+    use crate::shape::{Ball, Compound, SharedShape};
+
+    fn two_ball_compound() -> Compound {
+        Compound::new(vec![
+            (
+                Isometry::translation(-3.0, 0.0, 0.0),
+                SharedShape::new(Ball::new(0.5)),
+            ),
+            (
+                Isometry::translation(3.0, 0.0, 0.0),
+                SharedShape::new(Ball::new(0.5)),
+            ),
+        ])
+    }
+
+    #[test]
+    fn matches_an_all_pairs_brute_force_search() {
+        let dispatcher = DefaultQueryDispatcher;
+        let g1 = two_ball_compound();
+        let g2 = two_ball_compound();
+        let pos12 = Isometry::translation(0.0, 2.5, 0.0);
+
+        let (p1, p2) = match closest_points_composite_shape_composite_shape(
+            &dispatcher, &pos12, &g1, &g2, 2.0,
+        ) {
+            ClosestPoints::WithinMargin(p1, p2) => (p1, p2),
+            other => panic!("expected WithinMargin, got {:?}", other),
+        };
+        // `p2` must come back in g2's own local frame, not g1's.
+        let dist = na::distance(&p1, &(pos12 * p2));
+
+        let offsets = [-3.0, 3.0];
+        let mut brute_force_dist = Real::MAX;
+
+        for &offset1 in &offsets {
+            for &offset2 in &offsets {
+                let part_pos1 = Isometry::translation(offset1, 0.0, 0.0);
+                let part_pos2 = Isometry::translation(offset2, 0.0, 0.0);
+                let pos_part1_part2 = part_pos1.inverse() * pos12 * part_pos2;
+
+                if let Ok(ClosestPoints::WithinMargin(p1, p2)) =
+                    dispatcher.closest_points(&pos_part1_part2, &Ball::new(0.5), &Ball::new(0.5), 2.0)
+                {
+                    let p1 = part_pos1 * p1;
+                    let p2 = pos12 * part_pos2 * p2;
+                    brute_force_dist = brute_force_dist.min(na::distance(&p1, &p2));
+                }
+            }
+        }
+
+        assert!((dist - brute_force_dist).abs() < 1.0e-5);
+    }
+}
@@ -1,6 +1,8 @@
 use crate::bounding_volume::SimdAabb;
 use crate::math::{Isometry, Real, SimdBool, SimdReal, Vector, SIMD_WIDTH};
-use crate::partitioning::{SimdBestFirstVisitStatus, SimdBestFirstVisitor};
+use crate::partitioning::{
+    SimdBestFirstVisitStatus, SimdBestFirstVisitor, SimdVisitStatus, SimdVisitor,
+};
 use crate::query::{ClosestPoints, QueryDispatcher};
 use crate::shape::{Shape, TypedSimdCompositeShape};
 use crate::utils::IsometryOpt;
@@ -15,6 +17,22 @@ pub fn closest_points_composite_shape_shape<D, G1>(
     g2: &dyn Shape,
     margin: Real,
 ) -> ClosestPoints
+where
+    D: ?Sized + QueryDispatcher,
+    G1: ?Sized + TypedSimdCompositeShape,
+{
+    closest_points_composite_shape_shape_with_part(dispatcher, pos12, g1, g2, margin).1
+}
+
+/// Closest points between a composite shape and any other shape, along with the id of the
+/// sub-part of `g1` that produced them.
+pub fn closest_points_composite_shape_shape_with_part<D, G1>(
+    dispatcher: &D,
+    pos12: &Isometry<Real>,
+    g1: &G1,
+    g2: &dyn Shape,
+    margin: Real,
+) -> (G1::PartId, ClosestPoints)
 where
     D: ?Sized + QueryDispatcher,
     G1: ?Sized + TypedSimdCompositeShape,
@@ -26,7 +44,6 @@ where
         .traverse_best_first(&mut visitor)
         .expect("The composite shape must not be empty.")
         .1
-         .1
 }
 
 /// Closest points between a shape and a composite shape.
@@ -44,6 +61,50 @@ where
     closest_points_composite_shape_shape(dispatcher, &pos12.inverse(), g2, g1, margin).flipped()
 }
 
+/// Closest points between a shape and a composite shape, along with the id of the sub-part
+/// of `g2` that produced them.
+///
+/// The part id refers to `g2`'s own part indexing; it is not remapped to `g1`.
+pub fn closest_points_shape_composite_shape_with_part<D, G2>(
+    dispatcher: &D,
+    pos12: &Isometry<Real>,
+    g1: &dyn Shape,
+    g2: &G2,
+    margin: Real,
+) -> (G2::PartId, ClosestPoints)
+where
+    D: ?Sized + QueryDispatcher,
+    G2: ?Sized + TypedSimdCompositeShape,
+{
+    let (part_id, pts) = closest_points_composite_shape_shape_with_part(
+        dispatcher,
+        &pos12.inverse(),
+        g2,
+        g1,
+        margin,
+    );
+    (part_id, pts.flipped())
+}
+
+/// All parts of a composite shape within `margin` of another shape.
+pub fn closest_points_composite_shape_shape_all<D, G1>(
+    dispatcher: &D,
+    pos12: &Isometry<Real>,
+    g1: &G1,
+    g2: &dyn Shape,
+    margin: Real,
+) -> Vec<(G1::PartId, ClosestPoints)>
+where
+    D: ?Sized + QueryDispatcher,
+    G1: ?Sized + TypedSimdCompositeShape,
+{
+    let mut visitor =
+        CompositeShapeAgainstShapeClosestPointsAllVisitor::new(dispatcher, pos12, g1, g2, margin);
+
+    g1.typed_qbvh().traverse_depth_first(&mut visitor);
+    visitor.results
+}
+
 /// A visitor for computing the closest points between a composite-shape and a shape.
 pub struct CompositeShapeAgainstShapeClosestPointsVisitor<'a, D: ?Sized, G1: ?Sized + 'a> {
     msum_shift: Vector<SimdReal>,
@@ -160,3 +221,197 @@ where
         }
     }
 }
+
+/// A visitor that collects every part of a composite shape within a margin of a shape.
+struct CompositeShapeAgainstShapeClosestPointsAllVisitor<'a, D: ?Sized, G1: ?Sized + 'a> {
+    msum_shift: Vector<SimdReal>,
+    msum_margin: Vector<SimdReal>,
+    margin: Real,
+
+    dispatcher: &'a D,
+    pos12: &'a Isometry<Real>,
+    g1: &'a G1,
+    g2: &'a dyn Shape,
+    results: Vec<(G1::PartId, ClosestPoints)>,
+}
+
+impl<'a, D, G1> CompositeShapeAgainstShapeClosestPointsAllVisitor<'a, D, G1>
+where
+    D: ?Sized + QueryDispatcher,
+    G1: ?Sized + TypedSimdCompositeShape,
+{
+    fn new(
+        dispatcher: &'a D,
+        pos12: &'a Isometry<Real>,
+        g1: &'a G1,
+        g2: &'a dyn Shape,
+        margin: Real,
+    ) -> Self {
+        let ls_aabb2 = g2.compute_aabb(pos12).loosened(margin);
+
+        Self {
+            msum_shift: Vector::splat(-ls_aabb2.center().coords),
+            msum_margin: Vector::splat(ls_aabb2.half_extents()),
+            margin,
+            dispatcher,
+            pos12,
+            g1,
+            g2,
+            results: Vec::new(),
+        }
+    }
+}
+
+impl<D, G1> SimdVisitor<G1::PartId, SimdAabb>
+    for CompositeShapeAgainstShapeClosestPointsAllVisitor<'_, D, G1>
+where
+    D: ?Sized + QueryDispatcher,
+    G1: ?Sized + TypedSimdCompositeShape,
+{
+    fn visit(
+        &mut self,
+        bv: &SimdAabb,
+        data: Option<[Option<&G1::PartId>; SIMD_WIDTH]>,
+    ) -> SimdVisitStatus {
+        // A node's Aabb overlaps g2's Aabb loosened by `margin` iff their Minkowski sum
+        // contains the origin, i.e. the distance from the Minkowski sum to the origin is 0.
+        let msum = SimdAabb {
+            mins: bv.mins + self.msum_shift + (-self.msum_margin),
+            maxs: bv.maxs + self.msum_shift + self.msum_margin,
+        };
+        let mask = msum.distance_to_origin().simd_le(SimdReal::splat(0.0));
+
+        if let Some(data) = data {
+            let bitmask = mask.bitmask();
+
+            for ii in 0..SIMD_WIDTH {
+                if (bitmask & (1 << ii)) != 0 && data[ii].is_some() {
+                    let part_id = *data[ii].unwrap();
+                    self.g1.map_untyped_part_at(part_id, |part_pos1, g1, _| {
+                        let pts = self.dispatcher.closest_points(
+                            &part_pos1.inv_mul(self.pos12),
+                            g1,
+                            self.g2,
+                            self.margin,
+                        );
+
+                        match pts {
+                            Ok(ClosestPoints::WithinMargin(ref p1, ref p2)) => {
+                                let p1 = part_pos1.transform_point(p1);
+                                self.results
+                                    .push((part_id, ClosestPoints::WithinMargin(p1, *p2)));
+                            }
+                            Ok(ClosestPoints::Intersecting) => {
+                                // Every leaf is visited at most once per depth-first traversal,
+                                // so there's no risk of double-counting a part here.
+                                self.results.push((part_id, ClosestPoints::Intersecting));
+                            }
+                            Err(_) | Ok(ClosestPoints::Disjoint) => {}
+                        };
+                    });
+                }
+            }
+        }
+
+        SimdVisitStatus::MaybeContinue(mask)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::query::DefaultQueryDispatcher;
+    use crate::shape::{Ball, Compound, SharedShape};
+
+    fn two_ball_compound() -> Compound {
+        Compound::new(vec![
+            (
+                Isometry::translation(-2.5, 0.0, 0.0),
+                SharedShape::new(Ball::new(0.5)),
+            ),
+            (
+                Isometry::translation(2.5, 0.0, 0.0),
+                SharedShape::new(Ball::new(0.5)),
+            ),
+        ])
+    }
+
+    #[test]
+    fn with_part_reports_the_closest_sub_shape() {
+        let dispatcher = DefaultQueryDispatcher;
+        let compound = two_ball_compound();
+        let query_ball = Ball::new(0.5);
+        // Sits right above the compound's second sub-ball (part id 1).
+        let pos12 = Isometry::translation(2.5, 0.0, 2.0);
+
+        let (part_id, pts) = closest_points_composite_shape_shape_with_part(
+            &dispatcher,
+            &pos12,
+            &compound,
+            &query_ball,
+            1.0,
+        );
+
+        assert_eq!(part_id, 1);
+        assert!(matches!(pts, ClosestPoints::WithinMargin(..)));
+        assert_eq!(closest_points_composite_shape_shape(&dispatcher, &pos12, &compound, &query_ball, 1.0), pts);
+    }
+
+    #[test]
+    fn all_gathers_every_part_within_margin() {
+        let dispatcher = DefaultQueryDispatcher;
+        // Three balls at x = -2, 0, 2, each with radius 0.5.
+        let compound = Compound::new(vec![
+            (
+                Isometry::translation(-2.0, 0.0, 0.0),
+                SharedShape::new(Ball::new(0.5)),
+            ),
+            (
+                Isometry::translation(0.0, 0.0, 0.0),
+                SharedShape::new(Ball::new(0.5)),
+            ),
+            (
+                Isometry::translation(2.0, 0.0, 0.0),
+                SharedShape::new(Ball::new(0.5)),
+            ),
+        ]);
+        let query_ball = Ball::new(0.5);
+        // Gaps to the parts are 0.5 (middle), 1.5, 1.5 (outer two).
+        let pos12 = Isometry::translation(0.0, 1.5, 0.0);
+
+        let all_within_2 =
+            closest_points_composite_shape_shape_all(&dispatcher, &pos12, &compound, &query_ball, 2.0);
+        assert_eq!(all_within_2.len(), 3);
+
+        let all_within_1 =
+            closest_points_composite_shape_shape_all(&dispatcher, &pos12, &compound, &query_ball, 1.0);
+        assert_eq!(all_within_1.len(), 1);
+        assert_eq!(all_within_1[0].0, 1);
+    }
+
+    #[test]
+    fn all_reports_every_simultaneously_intersecting_part() {
+        let dispatcher = DefaultQueryDispatcher;
+        // Two overlapping balls at x = -0.25 and 0.25, radius 0.5 each.
+        let compound = Compound::new(vec![
+            (
+                Isometry::translation(-0.25, 0.0, 0.0),
+                SharedShape::new(Ball::new(0.5)),
+            ),
+            (
+                Isometry::translation(0.25, 0.0, 0.0),
+                SharedShape::new(Ball::new(0.5)),
+            ),
+        ]);
+        // A large query ball overlapping both sub-balls at once.
+        let query_ball = Ball::new(1.0);
+        let pos12 = Isometry::translation(0.0, 0.0, 0.0);
+
+        let all =
+            closest_points_composite_shape_shape_all(&dispatcher, &pos12, &compound, &query_ball, 0.0);
+        assert_eq!(all.len(), 2);
+        assert!(all.iter().all(|(_, pts)| matches!(pts, ClosestPoints::Intersecting)));
+        assert_eq!(all[0].0, 0);
+        assert_eq!(all[1].0, 1);
+    }
+}
@@ -0,0 +1,162 @@
+use crate::bounding_volume::SimdAabb;
+use crate::math::{Point, Real, SimdBool, SimdReal, Vector, SIMD_WIDTH};
+use crate::partitioning::{SimdBestFirstVisitStatus, SimdBestFirstVisitor};
+use crate::query::PointProjection;
+use crate::shape::TypedSimdCompositeShape;
+use na;
+use simba::simd::{SimdBool as _, SimdPartialOrd, SimdValue};
+
+/// Projects a point on a composite shape.
+pub fn project_point_composite_shape<G1>(
+    g1: &G1,
+    point: &Point<Real>,
+    solid: bool,
+) -> PointProjection
+where
+    G1: ?Sized + TypedSimdCompositeShape,
+{
+    let mut visitor = CompositeClosestPointVisitor::new(g1, point, solid);
+
+    g1.typed_qbvh()
+        .traverse_best_first(&mut visitor)
+        .expect("The composite shape must not be empty.")
+        .1
+}
+
+/// A visitor for projecting a point on a composite shape.
+pub struct CompositeClosestPointVisitor<'a, G1: ?Sized + 'a> {
+    point_shift: Vector<SimdReal>,
+    point: &'a Point<Real>,
+    solid: bool,
+    g1: &'a G1,
+}
+
+impl<'a, G1> CompositeClosestPointVisitor<'a, G1>
+where
+    G1: ?Sized + TypedSimdCompositeShape,
+{
+    /// Initializes a visitor for projecting a point on the given composite shape.
+    pub fn new(g1: &'a G1, point: &'a Point<Real>, solid: bool) -> Self {
+        Self {
+            point_shift: Vector::splat(-point.coords),
+            point,
+            solid,
+            g1,
+        }
+    }
+}
+
+impl<G1> SimdBestFirstVisitor<G1::PartId, SimdAabb> for CompositeClosestPointVisitor<'_, G1>
+where
+    G1: ?Sized + TypedSimdCompositeShape,
+{
+    type Result = PointProjection;
+
+    fn visit(
+        &mut self,
+        best: Real,
+        bv: &SimdAabb,
+        data: Option<[Option<&G1::PartId>; SIMD_WIDTH]>,
+    ) -> SimdBestFirstVisitStatus<Self::Result> {
+        // Shift the node's Aabb so that `self.point` sits at the origin, and reuse the
+        // Minkowski-sum-to-origin distance as the Aabb-to-point lower bound.
+        let shifted = SimdAabb {
+            mins: bv.mins + self.point_shift,
+            maxs: bv.maxs + self.point_shift,
+        };
+        let dist = shifted.distance_to_origin();
+        let mask = dist.simd_lt(SimdReal::splat(best));
+
+        if let Some(data) = data {
+            let bitmask = mask.bitmask();
+            let mut weights = [0.0; SIMD_WIDTH];
+            let mut mask = [false; SIMD_WIDTH];
+            let mut results = [None; SIMD_WIDTH];
+            let mut found_inside = None;
+
+            for ii in 0..SIMD_WIDTH {
+                if (bitmask & (1 << ii)) != 0 && data[ii].is_some() {
+                    let part_id = *data[ii].unwrap();
+                    self.g1.map_untyped_part_at(part_id, |part_pos1, g1, _| {
+                        let proj = g1.project_point(part_pos1, self.point, self.solid);
+
+                        if self.solid && proj.is_inside {
+                            found_inside = Some(proj);
+                        }
+
+                        weights[ii] = na::distance(&proj.point, self.point);
+                        results[ii] = Some(proj);
+                        mask[ii] = true;
+                    });
+
+                    if let Some(proj) = found_inside {
+                        return SimdBestFirstVisitStatus::ExitEarly(Some(proj));
+                    }
+                }
+            }
+
+            SimdBestFirstVisitStatus::MaybeContinue {
+                weights: SimdReal::from(weights),
+                mask: SimdBool::from(mask),
+                results,
+            }
+        } else {
+            SimdBestFirstVisitStatus::MaybeContinue {
+                weights: dist,
+                mask,
+                results: [None; SIMD_WIDTH],
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::math::Isometry;
+    use crate::shape::{Ball, Compound, SharedShape};
+
+    fn two_ball_compound() -> Compound {
+        Compound::new(vec![
+            (
+                Isometry::translation(-3.0, 0.0, 0.0),
+                SharedShape::new(Ball::new(0.5)),
+            ),
+            (
+                Isometry::translation(3.0, 0.0, 0.0),
+                SharedShape::new(Ball::new(0.5)),
+            ),
+        ])
+    }
+
+    #[test]
+    fn outside_point_projects_to_the_nearest_ball_surface() {
+        let compound = two_ball_compound();
+        // 1.0 to the right of the right-hand ball's surface.
+        let point = Point::new(4.5, 0.0, 0.0);
+
+        let proj = project_point_composite_shape(&compound, &point, true);
+        assert!(!proj.is_inside);
+        assert!((na::distance(&proj.point, &Point::new(3.5, 0.0, 0.0))) < 1.0e-4);
+    }
+
+    #[test]
+    fn solid_projection_of_an_interior_point_returns_the_point_itself() {
+        let compound = two_ball_compound();
+        let point = Point::new(3.0, 0.0, 0.0);
+
+        let proj = project_point_composite_shape(&compound, &point, true);
+        assert!(proj.is_inside);
+        assert_eq!(proj.point, point);
+    }
+
+    #[test]
+    fn hollow_projection_of_an_interior_point_projects_to_the_surface() {
+        let compound = two_ball_compound();
+        let point = Point::new(3.0, 0.0, 0.0);
+
+        let proj = project_point_composite_shape(&compound, &point, false);
+        assert!(!proj.is_inside);
+        assert!((na::distance(&proj.point, &point) - 0.5).abs() < 1.0e-4);
+    }
+}
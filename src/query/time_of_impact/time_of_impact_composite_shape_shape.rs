@@ -0,0 +1,233 @@
+use crate::bounding_volume::SimdAabb;
+use crate::math::{Isometry, Real, SimdBool, SimdReal, Vector, SIMD_WIDTH};
+use crate::partitioning::{SimdBestFirstVisitStatus, SimdBestFirstVisitor};
+use crate::query::{QueryDispatcher, TOI};
+use crate::shape::{Shape, TypedSimdCompositeShape};
+use simba::simd::{SimdBool as _, SimdPartialOrd, SimdValue};
+
+/// Time of impact between a composite shape and a shape under a relative translational motion.
+pub fn time_of_impact_composite_shape_shape<D, G1>(
+    dispatcher: &D,
+    pos12: &Isometry<Real>,
+    vel12: &Vector<Real>,
+    g1: &G1,
+    g2: &dyn Shape,
+    max_toi: Real,
+    target_distance: Real,
+) -> Option<TOI>
+where
+    D: ?Sized + QueryDispatcher,
+    G1: ?Sized + TypedSimdCompositeShape,
+{
+    let mut visitor = CompositeShapeAgainstShapeTOIVisitor::new(
+        dispatcher,
+        pos12,
+        vel12,
+        g1,
+        g2,
+        max_toi,
+        target_distance,
+    );
+
+    g1.typed_qbvh()
+        .traverse_best_first(&mut visitor)
+        .map(|h| h.1)
+}
+
+/// A visitor for finding the time of impact between a composite shape and a shape, under a
+/// relative translational motion of `g2` with respect to `g1`.
+struct CompositeShapeAgainstShapeTOIVisitor<'a, D: ?Sized, G1: ?Sized + 'a> {
+    msum_shift: Vector<SimdReal>,
+    msum_margin: Vector<SimdReal>,
+    inv_vel12_norm: Real,
+
+    dispatcher: &'a D,
+    pos12: &'a Isometry<Real>,
+    vel12: &'a Vector<Real>,
+    g1: &'a G1,
+    g2: &'a dyn Shape,
+    max_toi: Real,
+    target_distance: Real,
+}
+
+impl<'a, D, G1> CompositeShapeAgainstShapeTOIVisitor<'a, D, G1>
+where
+    D: ?Sized + QueryDispatcher,
+    G1: ?Sized + TypedSimdCompositeShape,
+{
+    /// Initializes a visitor for finding the time of impact between a composite shape and a shape.
+    fn new(
+        dispatcher: &'a D,
+        pos12: &'a Isometry<Real>,
+        vel12: &'a Vector<Real>,
+        g1: &'a G1,
+        g2: &'a dyn Shape,
+        max_toi: Real,
+        target_distance: Real,
+    ) -> Self {
+        let ls_aabb2 = g2.compute_aabb(pos12);
+        let vel12_norm = vel12.norm();
+
+        Self {
+            msum_shift: Vector::splat(-ls_aabb2.center().coords),
+            msum_margin: Vector::splat(ls_aabb2.half_extents()),
+            inv_vel12_norm: if vel12_norm > 0.0 {
+                1.0 / vel12_norm
+            } else {
+                0.0
+            },
+            dispatcher,
+            pos12,
+            vel12,
+            g1,
+            g2,
+            max_toi,
+            target_distance,
+        }
+    }
+}
+
+impl<D, G1> SimdBestFirstVisitor<G1::PartId, SimdAabb>
+    for CompositeShapeAgainstShapeTOIVisitor<'_, D, G1>
+where
+    D: ?Sized + QueryDispatcher,
+    G1: ?Sized + TypedSimdCompositeShape,
+{
+    type Result = TOI;
+
+    fn visit(
+        &mut self,
+        best: Real,
+        bv: &SimdAabb,
+        data: Option<[Option<&G1::PartId>; SIMD_WIDTH]>,
+    ) -> SimdBestFirstVisitStatus<Self::Result> {
+        // Lower-bound the earliest time this node's Aabb and g2's Aabb can close to within
+        // `target_distance` of each other: the current gap between them (the same
+        // Minkowski-sum-to-origin distance used for closest-points), less the target
+        // distance, can close no faster than at the relative linear speed `|vel12|`.
+        let msum = SimdAabb {
+            mins: bv.mins + self.msum_shift + (-self.msum_margin),
+            maxs: bv.maxs + self.msum_shift + self.msum_margin,
+        };
+        let gap = msum.distance_to_origin();
+        let remaining_gap = (gap - SimdReal::splat(self.target_distance)).simd_max(SimdReal::splat(0.0));
+        let earliest_impact = remaining_gap * SimdReal::splat(self.inv_vel12_norm);
+        let mask = earliest_impact.simd_le(SimdReal::splat(best.min(self.max_toi)));
+
+        if let Some(data) = data {
+            let bitmask = mask.bitmask();
+            let mut weights = [0.0; SIMD_WIDTH];
+            let mut mask = [false; SIMD_WIDTH];
+            let mut results = [None; SIMD_WIDTH];
+            let mut impact_now = None;
+
+            for ii in 0..SIMD_WIDTH {
+                if (bitmask & (1 << ii)) != 0 && data[ii].is_some() {
+                    let part_id = *data[ii].unwrap();
+                    self.g1.map_untyped_part_at(part_id, |part_pos1, g1, _| {
+                        let toi = self.dispatcher.time_of_impact(
+                            &part_pos1.inv_mul(self.pos12),
+                            &part_pos1.inverse_transform_vector(self.vel12),
+                            g1,
+                            self.g2,
+                            self.max_toi,
+                            self.target_distance,
+                        );
+
+                        if let Ok(Some(toi)) = toi {
+                            weights[ii] = toi.toi;
+                            mask[ii] = true;
+
+                            if toi.toi == 0.0 {
+                                impact_now = Some(toi.clone());
+                            }
+
+                            results[ii] = Some(toi);
+                        }
+                    });
+
+                    if impact_now.is_some() {
+                        return SimdBestFirstVisitStatus::ExitEarly(impact_now);
+                    }
+                }
+            }
+
+            SimdBestFirstVisitStatus::MaybeContinue {
+                weights: SimdReal::from(weights),
+                mask: SimdBool::from(mask),
+                results,
+            }
+        } else {
+            SimdBestFirstVisitStatus::MaybeContinue {
+                weights: earliest_impact,
+                mask,
+                results: [None; SIMD_WIDTH],
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::query::DefaultQueryDispatcher;
+    use crate::shape::{Ball, Compound, SharedShape};
+
+    fn two_ball_compound() -> Compound {
+        Compound::new(vec![
+            (
+                Isometry::translation(-4.0, 0.0, 0.0),
+                SharedShape::new(Ball::new(0.5)),
+            ),
+            (
+                Isometry::translation(4.0, 0.0, 0.0),
+                SharedShape::new(Ball::new(0.5)),
+            ),
+        ])
+    }
+
+    #[test]
+    fn matches_the_analytic_sphere_vs_sphere_toi() {
+        let dispatcher = DefaultQueryDispatcher;
+        let compound = two_ball_compound();
+        let query_ball = Ball::new(0.5);
+        let pos12 = Isometry::translation(-10.0, 0.0, 0.0);
+        let vel12 = Vector::new(1.0, 0.0, 0.0);
+
+        // Gap between the query ball and the nearer (left) sub-ball is 6.0 - 0.5 - 0.5 = 5.0,
+        // closing at relative speed 1.0.
+        let toi = time_of_impact_composite_shape_shape(
+            &dispatcher,
+            &pos12,
+            &vel12,
+            &compound,
+            &query_ball,
+            100.0,
+            0.0,
+        )
+        .expect("expected an impact");
+        assert!((toi.toi - 5.0).abs() < 1.0e-4);
+    }
+
+    #[test]
+    fn target_distance_stops_short_of_contact() {
+        let dispatcher = DefaultQueryDispatcher;
+        let compound = two_ball_compound();
+        let query_ball = Ball::new(0.5);
+        let pos12 = Isometry::translation(-10.0, 0.0, 0.0);
+        let vel12 = Vector::new(1.0, 0.0, 0.0);
+
+        // Stopping 0.5 short of contact should report a Toi that's 0.5 earlier.
+        let toi = time_of_impact_composite_shape_shape(
+            &dispatcher,
+            &pos12,
+            &vel12,
+            &compound,
+            &query_ball,
+            100.0,
+            0.5,
+        )
+        .expect("expected an impact");
+        assert!((toi.toi - 4.5).abs() < 1.0e-4);
+    }
+}